@@ -0,0 +1,52 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use serde::{Deserialize, Serialize};
+
+/// The fields of an address record that are meaningful to the user and
+/// that get synced verbatim between clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Address {
+    pub guid: String,
+    pub name: String,
+    pub organization: String,
+    pub street_address: String,
+    pub address_level3: String,
+    pub address_level2: String,
+    pub address_level1: String,
+    pub postal_code: String,
+    pub country: String,
+    pub tel: String,
+    pub email: String,
+}
+
+/// The full record we keep in the `addresses_data` table, including the
+/// bookkeeping fields that never get shown to the user directly.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InternalAddress {
+    #[serde(flatten)]
+    pub address: Address,
+    pub time_created: i64,
+    pub time_last_used: i64,
+    pub time_last_modified: i64,
+    pub times_used: i64,
+    /// Number of local changes made since the last successful sync. Zero
+    /// means the record is unchanged since it was last synced. Whether a
+    /// record has ever been synced is tracked separately, by whether it
+    /// has a mirror row (see `db::AddressesStore::get_all_unsynced_local`).
+    pub sync_change_counter: i64,
+}
+
+impl std::ops::Deref for InternalAddress {
+    type Target = Address;
+    fn deref(&self) -> &Address {
+        &self.address
+    }
+}
+
+impl std::ops::DerefMut for InternalAddress {
+    fn deref_mut(&mut self) -> &mut Address {
+        &mut self.address
+    }
+}
@@ -0,0 +1,176 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::address::{Address, InternalAddress};
+use crate::error::*;
+use crate::schema::{self, AddressesConnectionInitializer};
+use rusqlite::{named_params, Connection, Row};
+use sql_support::open_database::open_database_with_flags;
+use std::path::Path;
+
+/// The store that owns the addresses database connection. This is the
+/// type embedders construct, and the one the sync engine and FFI layer
+/// both operate through.
+pub struct AddressesStore {
+    pub db: Connection,
+}
+
+impl AddressesStore {
+    pub fn new(db_path: impl AsRef<Path>) -> Result<Self> {
+        let db = open_database_with_flags(
+            db_path,
+            Default::default(),
+            &AddressesConnectionInitializer,
+        )?;
+        schema::check_schema_compatibility(&db)?;
+        Ok(Self { db })
+    }
+
+    pub fn new_with_connection(db: Connection) -> Result<Self> {
+        AddressesConnectionInitializer.init(&db).map_err(Error::from)?;
+        schema::check_schema_compatibility(&db)?;
+        Ok(Self { db })
+    }
+
+    pub fn get_local(&self, guid: &str) -> Result<Option<InternalAddress>> {
+        get_record(&self.db, "addresses_data", guid)
+    }
+
+    pub fn get_mirror(&self, guid: &str) -> Result<Option<InternalAddress>> {
+        get_record(&self.db, "addresses_mirror", guid)
+    }
+
+    /// All locally-changed records that still need to be synced up.
+    pub fn get_all_dirty_local(&self) -> Result<Vec<InternalAddress>> {
+        let mut stmt = self.db.prepare(
+            "SELECT * FROM addresses_data WHERE sync_change_counter > 0",
+        )?;
+        let rows = stmt.query_and_then([], from_row)?;
+        rows.collect()
+    }
+
+    pub fn put_local(&self, record: &InternalAddress) -> Result<()> {
+        upsert_record(&self.db, "addresses_data", record, true)
+    }
+
+    /// Writes a record into the mirror without touching its
+    /// `sync_change_counter`, since the mirror table doesn't have one.
+    pub fn put_mirror(&self, record: &InternalAddress) -> Result<()> {
+        upsert_record(&self.db, "addresses_mirror", record, false)
+    }
+
+    /// Local records that have never been synced, identified by having no
+    /// corresponding row in the mirror. These are the only records the
+    /// dedupe pass can safely fold into an incoming remote record by
+    /// swapping in its guid.
+    pub fn get_all_unsynced_local(&self) -> Result<Vec<InternalAddress>> {
+        let mut stmt = self.db.prepare(
+            "SELECT * FROM addresses_data WHERE guid NOT IN (SELECT guid FROM addresses_mirror)",
+        )?;
+        let rows = stmt.query_and_then([], from_row)?;
+        rows.collect()
+    }
+
+    /// Renames a local record's guid in place (used when we coalesce it
+    /// onto an incoming remote duplicate) without disturbing any of its
+    /// other fields.
+    pub fn rename_local_guid(&self, old_guid: &str, new_guid: &str) -> Result<()> {
+        self.db.execute(
+            "UPDATE addresses_data SET guid = :new_guid WHERE guid = :old_guid",
+            named_params! { ":old_guid": old_guid, ":new_guid": new_guid },
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_local(&self, guid: &str) -> Result<()> {
+        self.db.execute(
+            "DELETE FROM addresses_data WHERE guid = :guid",
+            named_params! { ":guid": guid },
+        )?;
+        Ok(())
+    }
+}
+
+fn field_columns(with_change_counter: bool) -> &'static str {
+    if with_change_counter {
+        "guid, name, organization, street_address, address_level3, address_level2,
+         address_level1, postal_code, country, tel, email, time_created,
+         time_last_used, time_last_modified, times_used, sync_change_counter"
+    } else {
+        "guid, name, organization, street_address, address_level3, address_level2,
+         address_level1, postal_code, country, tel, email, time_created,
+         time_last_used, time_last_modified, times_used"
+    }
+}
+
+pub(crate) fn from_row(row: &Row<'_>) -> Result<InternalAddress> {
+    Ok(InternalAddress {
+        address: Address {
+            guid: row.get("guid")?,
+            name: row.get("name")?,
+            organization: row.get("organization")?,
+            street_address: row.get("street_address")?,
+            address_level3: row.get("address_level3")?,
+            address_level2: row.get("address_level2")?,
+            address_level1: row.get("address_level1")?,
+            postal_code: row.get("postal_code")?,
+            country: row.get("country")?,
+            tel: row.get("tel")?,
+            email: row.get("email")?,
+        },
+        time_created: row.get("time_created")?,
+        time_last_used: row.get("time_last_used")?,
+        time_last_modified: row.get("time_last_modified")?,
+        times_used: row.get("times_used")?,
+        sync_change_counter: row.get("sync_change_counter").unwrap_or(0),
+    })
+}
+
+fn get_record(db: &Connection, table: &str, guid: &str) -> Result<Option<InternalAddress>> {
+    let sql = format!("SELECT * FROM {table} WHERE guid = :guid");
+    let mut stmt = db.prepare(&sql)?;
+    let mut rows = stmt.query_and_then(named_params! { ":guid": guid }, from_row)?;
+    rows.next().transpose()
+}
+
+fn upsert_record(
+    db: &Connection,
+    table: &str,
+    record: &InternalAddress,
+    with_change_counter: bool,
+) -> Result<()> {
+    let columns = field_columns(with_change_counter);
+    let placeholders = if with_change_counter {
+        ":guid, :name, :organization, :street_address, :address_level3, :address_level2,
+         :address_level1, :postal_code, :country, :tel, :email, :time_created,
+         :time_last_used, :time_last_modified, :times_used, :sync_change_counter"
+    } else {
+        ":guid, :name, :organization, :street_address, :address_level3, :address_level2,
+         :address_level1, :postal_code, :country, :tel, :email, :time_created,
+         :time_last_used, :time_last_modified, :times_used"
+    };
+    let sql = format!("INSERT OR REPLACE INTO {table} ({columns}) VALUES ({placeholders})");
+    db.execute(
+        &sql,
+        named_params! {
+            ":guid": record.guid,
+            ":name": record.name,
+            ":organization": record.organization,
+            ":street_address": record.street_address,
+            ":address_level3": record.address_level3,
+            ":address_level2": record.address_level2,
+            ":address_level1": record.address_level1,
+            ":postal_code": record.postal_code,
+            ":country": record.country,
+            ":tel": record.tel,
+            ":email": record.email,
+            ":time_created": record.time_created,
+            ":time_last_used": record.time_last_used,
+            ":time_last_modified": record.time_last_modified,
+            ":times_used": record.times_used,
+            ":sync_change_counter": record.sync_change_counter,
+        },
+    )?;
+    Ok(())
+}
@@ -0,0 +1,216 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Detects local records that are semantically the same address as an
+//! incoming remote record but were never linked to it (typically because
+//! the same physical address was saved independently on two clients).
+//! Rather than syncing in as a second, near-identical entry, we coalesce
+//! the local row onto the remote guid.
+
+use crate::address::{Address, InternalAddress};
+use crate::util::collapse_whitespace;
+
+/// A normalized comparison key over the fields that identify a physical
+/// address, insensitive to whitespace and casing differences that don't
+/// change what the address actually is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DedupeKey(Vec<String>);
+
+impl DedupeKey {
+    pub fn for_address(address: &Address) -> Self {
+        let parts = [
+            normalize_name_field(&address.name),
+            normalize_name_field(&address.organization),
+            normalize_name_field(&address.street_address),
+            normalize_name_field(&address.address_level3),
+            normalize_name_field(&address.address_level2),
+            normalize_name_field(&address.address_level1),
+            normalize_postal_code(&address.postal_code),
+            normalize_name_field(&address.country),
+            normalize_phone(&address.tel),
+            normalize_name_field(&address.email),
+        ];
+        DedupeKey(parts.to_vec())
+    }
+
+    /// Two keys match when every field agrees after normalization, where a
+    /// field that's empty on either side matches anything - one client may
+    /// simply not have recorded it.
+    pub fn matches(&self, other: &DedupeKey) -> bool {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .all(|(a, b)| a == b || a.is_empty() || b.is_empty())
+    }
+}
+
+fn normalize_name_field(s: &str) -> String {
+    collapse_whitespace(s).to_lowercase()
+}
+
+/// Strips everything but digits, so "(555) 123-4567" and "555-123-4567"
+/// compare equal.
+fn normalize_phone(s: &str) -> String {
+    s.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+/// Strips whitespace and lowercases, so "V8V 3K2" and "v8v3k2" compare
+/// equal.
+fn normalize_postal_code(s: &str) -> String {
+    s.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Given a local record that has never been synced (no mirror entry, so
+/// its guid can't already be the same as `remote`'s), finds whether it's
+/// a duplicate of `remote` and if so returns the coalesced record: the
+/// remote's guid, the union of non-empty fields (remote preferred when
+/// both sides have a value), and the max of the usage counters. Callers
+/// are expected to only offer candidates with no corresponding mirror
+/// row - a record that's already been synced once needs the normal
+/// three-way merge path instead of a guid swap.
+pub fn coalesce_if_duplicate(
+    local: &InternalAddress,
+    remote: &InternalAddress,
+) -> Option<InternalAddress> {
+    if !DedupeKey::for_address(&local.address).matches(&DedupeKey::for_address(&remote.address)) {
+        return None;
+    }
+
+    let mut coalesced = remote.clone();
+    coalesced.guid = remote.guid.clone();
+    union_non_empty(&mut coalesced.address, &local.address);
+    coalesced.times_used = local.times_used.max(remote.times_used);
+    coalesced.time_created = local.time_created.min(remote.time_created);
+    coalesced.time_last_used = local.time_last_used.max(remote.time_last_used);
+    coalesced.time_last_modified = local.time_last_modified.max(remote.time_last_modified);
+    // The union may have pulled in fields the server's copy doesn't have
+    // yet (e.g. only the local side recorded an organization). Only treat
+    // the coalesced record as clean when it's identical to what the
+    // server sent us - otherwise it needs to go back up, so leave it
+    // dirty and let the caller queue it as an outgoing change.
+    coalesced.sync_change_counter = if coalesced.address == remote.address { 0 } else { 1 };
+
+    Some(coalesced)
+}
+
+/// Fills any field that's empty on `into` with the corresponding value
+/// from `from`, so a dedupe doesn't lose detail one side captured and the
+/// other didn't (e.g. only one side recorded an organization).
+fn union_non_empty(into: &mut Address, from: &Address) {
+    macro_rules! fill {
+        ($field:ident) => {
+            if into.$field.is_empty() && !from.$field.is_empty() {
+                into.$field = from.$field.clone();
+            }
+        };
+    }
+    fill!(name);
+    fill!(organization);
+    fill!(street_address);
+    fill!(address_level3);
+    fill!(address_level2);
+    fill!(address_level1);
+    fill!(postal_code);
+    fill!(country);
+    fill!(tel);
+    fill!(email);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(name: &str, street: &str, tel: &str, postal_code: &str) -> Address {
+        Address {
+            name: name.into(),
+            street_address: street.into(),
+            tel: tel.into(),
+            postal_code: postal_code.into(),
+            ..Default::default()
+        }
+    }
+
+    fn local_record(mut address: Address) -> InternalAddress {
+        address.guid = "localguid01".into();
+        InternalAddress { address, ..Default::default() }
+    }
+
+    #[test]
+    fn normalize_phone_strips_punctuation() {
+        assert_eq!(normalize_phone("(555) 123-4567"), "5551234567");
+        assert_eq!(normalize_phone("+1 555.123.4567"), "15551234567");
+        assert_eq!(normalize_phone(""), "");
+        assert_eq!(normalize_phone("ext."), "");
+    }
+
+    #[test]
+    fn normalize_postal_code_strips_whitespace_and_lowercases() {
+        assert_eq!(normalize_postal_code("V8V 3K2"), "v8v3k2");
+        assert_eq!(normalize_postal_code("v8v3k2"), "v8v3k2");
+        assert_eq!(normalize_postal_code(""), "");
+    }
+
+    #[test]
+    fn normalize_name_field_collapses_whitespace_and_lowercases() {
+        assert_eq!(normalize_name_field("  Alice   Smith "), "alice smith");
+        assert_eq!(normalize_name_field(""), "");
+    }
+
+    #[test]
+    fn coalesce_recognizes_differently_formatted_duplicate() {
+        let local = local_record(address("Alice Smith", "1 Main St", "(555) 123-4567", "V8V 3K2"));
+        let mut remote = InternalAddress {
+            address: address("Alice Smith", "1 Main St", "555-123-4567", "v8v3k2"),
+            ..Default::default()
+        };
+        remote.guid = "remoteguid1".into();
+
+        let coalesced = coalesce_if_duplicate(&local, &remote).expect("should be a duplicate");
+        assert_eq!(coalesced.guid, "remoteguid1");
+    }
+
+    #[test]
+    fn coalesce_returns_none_for_distinct_addresses() {
+        let local = local_record(address("Alice Smith", "1 Main St", "", ""));
+        let remote = InternalAddress {
+            address: address("Bob Jones", "2 Other Ave", "", ""),
+            ..Default::default()
+        };
+        assert!(coalesce_if_duplicate(&local, &remote).is_none());
+    }
+
+    #[test]
+    fn coalesce_unions_fields_and_marks_dirty_when_remote_lacks_them() {
+        let local = local_record(address("Alice Smith", "1 Main St", "5551234567", ""));
+        let mut remote = InternalAddress {
+            address: address("Alice Smith", "1 Main St", "", ""),
+            ..Default::default()
+        };
+        remote.guid = "remoteguid1".into();
+
+        let coalesced = coalesce_if_duplicate(&local, &remote).expect("should be a duplicate");
+        // The remote copy never recorded a phone number - the union
+        // should carry it over from the local copy.
+        assert_eq!(coalesced.tel, "5551234567");
+        // Since the coalesced record now differs from what the server
+        // sent, it must stay dirty so it gets uploaded.
+        assert_ne!(coalesced.sync_change_counter, 0);
+    }
+
+    #[test]
+    fn coalesce_stays_clean_when_identical_to_remote() {
+        let local = local_record(address("Alice Smith", "1 Main St", "5551234567", "V8V 3K2"));
+        let mut remote = InternalAddress {
+            address: address("Alice Smith", "1 Main St", "5551234567", "V8V 3K2"),
+            ..Default::default()
+        };
+        remote.guid = "remoteguid1".into();
+
+        let coalesced = coalesce_if_duplicate(&local, &remote).expect("should be a duplicate");
+        assert_eq!(coalesced.sync_change_counter, 0);
+    }
+}
@@ -0,0 +1,136 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::address::InternalAddress;
+use crate::db::AddressesStore;
+use crate::dedupe;
+use crate::error::Result;
+use crate::update_plan::UpdatePlan;
+
+/// Drives a single sync pass against an `AddressesStore`: reconciles
+/// incoming remote records with whatever's local, applies the result, and
+/// reports what needs to go back up to the server.
+pub struct AddressesSyncEngine<'a> {
+    store: &'a AddressesStore,
+}
+
+/// What a sync pass produced, once its writes have been applied.
+pub struct ApplyIncomingOutcome {
+    /// The records that need to be uploaded so the local and remote
+    /// stores converge - merged records, and coalesced duplicates that
+    /// picked up fields the server doesn't have yet.
+    pub outgoing: Vec<InternalAddress>,
+    /// Guids where a three-way merge hit a genuine conflict (see
+    /// `update_plan::three_way_merge`), surfaced so callers can log or
+    /// report it rather than have it pass silently.
+    pub conflicted_guids: Vec<String>,
+}
+
+impl<'a> AddressesSyncEngine<'a> {
+    pub fn new(store: &'a AddressesStore) -> Self {
+        Self { store }
+    }
+
+    /// Reconciles a batch of incoming remote records against the local
+    /// store and mirror, applies the merged results, and returns the
+    /// records that need to be uploaded so both stores converge.
+    ///
+    /// Runs inside a single transaction, so a failure partway through a
+    /// batch can't leave a guid rename or local write committed without
+    /// its matching mirror update - that would permanently desync the
+    /// mirror baseline later three-way merges rely on as the common
+    /// ancestor.
+    pub fn apply_incoming(&self, incoming: Vec<InternalAddress>) -> Result<ApplyIncomingOutcome> {
+        let tx = self.store.db.unchecked_transaction()?;
+        let mut plan = UpdatePlan::default();
+        // Records created locally and never synced - the only candidates
+        // a remote record can be coalesced onto instead of inserted
+        // alongside as a near-duplicate.
+        let mut dedupe_candidates = self.store.get_all_unsynced_local()?;
+
+        for remote in incoming {
+            let local = self.store.get_local(&remote.guid)?;
+            if local.is_none() {
+                let duplicate = dedupe_candidates
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, candidate)| {
+                        dedupe::coalesce_if_duplicate(candidate, &remote).map(|merged| (i, merged))
+                    });
+                if let Some((i, coalesced)) = duplicate {
+                    let candidate = dedupe_candidates.remove(i);
+                    self.store.rename_local_guid(&candidate.guid, &remote.guid)?;
+                    // The mirror records what the server actually has,
+                    // which is `remote` - not the coalesced record, which
+                    // may carry fields the server doesn't know about yet.
+                    // Otherwise a later sync pass would see local/mirror
+                    // agree and never notice those fields need uploading.
+                    if coalesced.sync_change_counter != 0 {
+                        plan.outgoing.push(coalesced.clone());
+                    }
+                    plan.mirror_updates.push(remote.clone());
+                    plan.local_updates.push(coalesced);
+                    continue;
+                }
+            }
+            let mirror = self.store.get_mirror(&remote.guid)?;
+            plan.plan_incoming(mirror, local, remote);
+        }
+        for record in &plan.local_updates {
+            self.store.put_local(record)?;
+        }
+        for record in &plan.mirror_updates {
+            self.store.put_mirror(record)?;
+        }
+        tx.commit()?;
+        Ok(ApplyIncomingOutcome {
+            outgoing: plan.outgoing,
+            conflicted_guids: plan.conflicted_guids,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn address(name: &str, street: &str, tel: &str) -> crate::address::Address {
+        crate::address::Address {
+            name: name.into(),
+            street_address: street.into(),
+            tel: tel.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn coalesced_duplicate_with_unioned_fields_is_reuploaded() {
+        let store = AddressesStore::new_with_connection(Connection::open_in_memory().unwrap())
+            .expect("open in-memory store");
+
+        // A record created locally, never synced (no mirror row), that
+        // recorded a phone number the server copy doesn't have.
+        let mut local = InternalAddress {
+            address: address("Alice Smith", "1 Main St", "5551234567"),
+            sync_change_counter: 1,
+            ..Default::default()
+        };
+        local.guid = "localguid01".into();
+        store.put_local(&local).unwrap();
+
+        let mut remote = InternalAddress {
+            address: address("Alice Smith", "1 Main St", ""),
+            ..Default::default()
+        };
+        remote.guid = "remoteguid1".into();
+
+        let engine = AddressesSyncEngine::new(&store);
+        let outcome = engine.apply_incoming(vec![remote]).unwrap();
+
+        assert_eq!(outcome.outgoing.len(), 1);
+        assert_eq!(outcome.outgoing[0].guid, "remoteguid1");
+        assert_eq!(outcome.outgoing[0].tel, "5551234567");
+    }
+}
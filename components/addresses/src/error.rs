@@ -0,0 +1,95 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Error synchronizing: {0}")]
+    SyncAdapterError(#[from] sync15::Error),
+
+    #[error("Error parsing JSON data: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Error executing SQL: {0}")]
+    SqlError(#[from] rusqlite::Error),
+
+    #[error("{0}")]
+    OpenDatabaseError(#[from] sql_support::open_database::Error),
+
+    #[error("A connection of this type is already open")]
+    ConnectionAlreadyOpen,
+
+    #[error("No record with guid exists: {0}")]
+    NoSuchRecord(String),
+
+    #[error(
+        "This database was written by a newer, incompatible version of this library \
+         (schema version {stored}, this build supports up to {supported})"
+    )]
+    IncompatibleSchemaVersion {
+        stored: semver::Version,
+        supported: semver::Version,
+    },
+
+    #[error("The operation was interrupted")]
+    Interrupted(#[from] interrupt_support::Interrupted),
+
+    #[error("Unexpected error: {0}")]
+    UnexpectedError(String),
+}
+
+// Allows other modules to do `throw!(Error::Foo)` instead of
+// `return Err(Error::Foo.into())`, matching the style used by our other
+// sync-enabled storage components.
+macro_rules! throw {
+    ($e:expr) => {
+        return Err(std::convert::From::from($e))
+    };
+}
+
+/// Stable integer codes returned across the FFI boundary, the same way
+/// rust-url's C API freezes one code per `ParseError` variant. These
+/// values must never be renumbered once shipped - embedding apps persist
+/// them (e.g. in crash reports) and match on them to decide things like
+/// "retry on a transient DB-locked failure" versus "surface a permanent
+/// corruption error to the user".
+pub mod error_codes {
+    pub const SUCCESS: i32 = 0;
+    pub const INTERRUPTED: i32 = 1;
+    pub const DATABASE_ERROR: i32 = 2;
+    pub const INVALID_RECORD: i32 = 3;
+    /// Reserved for parity with sibling components (e.g. credit cards)
+    /// that do have a keying/crypto error path; addresses has none today.
+    pub const CRYPTO_ERROR: i32 = 4;
+    /// A database written by a newer, incompatible schema version. This is
+    /// permanent - retrying won't help, since this build will never be
+    /// able to read the profile - so it's kept distinct from the
+    /// transient `DATABASE_ERROR` code an embedder might reasonably retry.
+    pub const INCOMPATIBLE_SCHEMA: i32 = 5;
+    pub const UNEXPECTED: i32 = 6;
+}
+
+/// Maps an error to one of the frozen `error_codes` constants.
+/// Implemented on `Error` (rather than just matching ad hoc at each FFI
+/// entry point) so every caller across the boundary agrees on the
+/// mapping.
+pub trait ErrorCode {
+    fn code(&self) -> i32;
+}
+
+impl ErrorCode for Error {
+    fn code(&self) -> i32 {
+        use error_codes::*;
+        match self {
+            Error::Interrupted(_) => INTERRUPTED,
+            Error::SqlError(_) | Error::OpenDatabaseError(_) => DATABASE_ERROR,
+            Error::IncompatibleSchemaVersion { .. } => INCOMPATIBLE_SCHEMA,
+            Error::NoSuchRecord(_) | Error::JsonError(_) => INVALID_RECORD,
+            Error::SyncAdapterError(_) | Error::ConnectionAlreadyOpen | Error::UnexpectedError(_) => {
+                UNEXPECTED
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Thin FFI entry points for embedding apps (currently consumed via the
+//! generated Kotlin/Swift bindings). Keep these small - they should just
+//! adapt `error::Result` to something that crosses the boundary cleanly.
+
+use crate::address::InternalAddress;
+use crate::db::AddressesStore;
+use crate::engine::AddressesSyncEngine;
+use crate::error::{error_codes, ErrorCode};
+
+/// Outcome of `apply_incoming_ffi`, crossing the boundary as a single
+/// value so callers get both the status code and - on success - the
+/// records the three-way merge/dedupe pass produced that still need to
+/// go back up to the server, plus any guids where a merge had to break a
+/// genuine conflict.
+pub struct ApplyIncomingFfiResult {
+    pub code: i32,
+    pub outgoing: Vec<InternalAddress>,
+    pub conflicted_guids: Vec<String>,
+}
+
+/// Runs a sync pass over `incoming`, applying it to `store`. `code` is
+/// `error_codes::SUCCESS` on success, or the failing `Error`'s stable
+/// `ErrorCode` otherwise, so embedders can branch on the failure class
+/// (e.g. retry on `DATABASE_ERROR`) without parsing the message string.
+/// On failure, `outgoing` and `conflicted_guids` are empty - none of the
+/// pass's writes were applied, since `apply_incoming` runs in a single
+/// transaction.
+pub fn apply_incoming_ffi(
+    store: &AddressesStore,
+    incoming: Vec<InternalAddress>,
+) -> ApplyIncomingFfiResult {
+    let engine = AddressesSyncEngine::new(store);
+    match engine.apply_incoming(incoming) {
+        Ok(outcome) => ApplyIncomingFfiResult {
+            code: error_codes::SUCCESS,
+            outgoing: outcome.outgoing,
+            conflicted_guids: outcome.conflicted_guids,
+        },
+        Err(e) => ApplyIncomingFfiResult {
+            code: e.code(),
+            outgoing: Vec::new(),
+            conflicted_guids: Vec::new(),
+        },
+    }
+}
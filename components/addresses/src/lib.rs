@@ -10,6 +10,7 @@ mod error;
 mod address;
 
 mod db;
+mod dedupe;
 mod engine;
 pub mod schema;
 mod update_plan;
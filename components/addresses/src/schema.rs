@@ -0,0 +1,242 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::error::{Error, Result};
+use rusqlite::{named_params, Connection, OptionalExtension};
+use sql_support::open_database::{self, ConnectionInitializer};
+
+/// The current schema version. Bump this whenever `CREATE_SCHEMA_SQL`
+/// changes in a way that requires existing databases to be migrated.
+pub const VERSION: u32 = 1;
+
+const SCHEMA_VERSION_META_KEY: &str = "schema_semver";
+
+/// The semantic version of the schema this build writes, recorded in
+/// `addresses_meta` so a later, older build can tell a newer-incompatible
+/// database apart from one it can just open directly. This tracks the
+/// crate's own version rather than a separately-maintained number, the
+/// same way `gkrust_utils` compares semver across the Gecko/Rust
+/// boundary.
+pub fn schema_semver() -> semver::Version {
+    semver::Version::parse(env!("CARGO_PKG_VERSION")).expect("CARGO_PKG_VERSION is valid semver")
+}
+
+const CREATE_SCHEMA_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS addresses_data (
+        guid                TEXT PRIMARY KEY,
+        name                TEXT NOT NULL,
+        organization        TEXT NOT NULL,
+        street_address      TEXT NOT NULL,
+        address_level3      TEXT NOT NULL,
+        address_level2      TEXT NOT NULL,
+        address_level1      TEXT NOT NULL,
+        postal_code         TEXT NOT NULL,
+        country             TEXT NOT NULL,
+        tel                 TEXT NOT NULL,
+        email               TEXT NOT NULL,
+        time_created        INTEGER NOT NULL,
+        time_last_used       INTEGER NOT NULL,
+        time_last_modified   INTEGER NOT NULL,
+        times_used           INTEGER NOT NULL DEFAULT 0,
+        sync_change_counter  INTEGER NOT NULL DEFAULT 1
+    );
+
+    -- The last-synced (\"mirror\") copy of each record, used as the common
+    -- ancestor when three-way-merging local and incoming remote changes.
+    CREATE TABLE IF NOT EXISTS addresses_mirror (
+        guid                TEXT PRIMARY KEY,
+        name                TEXT NOT NULL,
+        organization        TEXT NOT NULL,
+        street_address      TEXT NOT NULL,
+        address_level3      TEXT NOT NULL,
+        address_level2      TEXT NOT NULL,
+        address_level1      TEXT NOT NULL,
+        postal_code         TEXT NOT NULL,
+        country             TEXT NOT NULL,
+        tel                 TEXT NOT NULL,
+        email               TEXT NOT NULL,
+        time_created        INTEGER NOT NULL,
+        time_last_used       INTEGER NOT NULL,
+        time_last_modified   INTEGER NOT NULL,
+        times_used           INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE TABLE IF NOT EXISTS addresses_tombstones (
+        guid        TEXT PRIMARY KEY,
+        time_deleted INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS addresses_meta (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+/// Wires the addresses schema up to `sql_support`'s generic
+/// create-or-migrate machinery, the same way the other storage components
+/// in this workspace do.
+pub struct AddressesConnectionInitializer;
+
+impl ConnectionInitializer for AddressesConnectionInitializer {
+    const NAME: &'static str = "addresses";
+    const END_VERSION: u32 = VERSION;
+
+    fn init(&self, db: &Connection) -> open_database::Result<()> {
+        db.execute_batch(CREATE_SCHEMA_SQL)?;
+        Ok(())
+    }
+
+    fn upgrade_from(&self, _db: &Connection, version: u32) -> open_database::Result<()> {
+        Err(open_database::Error::IncompatibleVersion(version))
+    }
+}
+
+pub fn create_schema(db: &Connection) -> Result<()> {
+    db.execute_batch(CREATE_SCHEMA_SQL)?;
+    Ok(())
+}
+
+fn get_stored_semver(db: &Connection) -> Result<Option<semver::Version>> {
+    let stored: Option<String> = db
+        .query_row(
+            "SELECT value FROM addresses_meta WHERE key = :key",
+            named_params! { ":key": SCHEMA_VERSION_META_KEY },
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(match stored {
+        Some(v) => Some(semver::Version::parse(&v).map_err(|e| {
+            Error::UnexpectedError(format!("invalid stored schema version {v:?}: {e}"))
+        })?),
+        None => None,
+    })
+}
+
+fn set_stored_semver(db: &Connection, version: &semver::Version) -> Result<()> {
+    db.execute(
+        "INSERT OR REPLACE INTO addresses_meta (key, value) VALUES (:key, :value)",
+        named_params! { ":key": SCHEMA_VERSION_META_KEY, ":value": version.to_string() },
+    )?;
+    Ok(())
+}
+
+/// A comparable key capturing just the part of a version that signals a
+/// breaking change, per semver's own pre-1.0 convention: once `major` is
+/// 0, `minor` carries that meaning instead, and once `major.minor` is
+/// `0.0`, every `patch` bump is breaking (`0.2.0` is breaking relative to
+/// `0.1.0` the same as `2.0.0` is to `1.0.0`; `0.0.2` is breaking relative
+/// to `0.0.1`). Two versions with an equal key are compatible with each
+/// other regardless of any trailing difference.
+fn breaking_key(version: &semver::Version) -> (u64, u64, u64) {
+    if version.major > 0 {
+        (version.major, 0, 0)
+    } else if version.minor > 0 {
+        (0, version.minor, 0)
+    } else {
+        (0, 0, version.patch)
+    }
+}
+
+/// Called on every open: compares the schema version recorded in this
+/// database against the version this build writes. A stored version with
+/// a newer breaking key (see `breaking_key`) means the database was
+/// written by code this build doesn't understand and may have been only
+/// half-migrated by it if we pressed on, so we refuse to open it rather
+/// than risk corrupting data. Compatible versions proceed regardless of
+/// any trailing difference, and the stored version is advanced to match
+/// this build's.
+pub fn check_schema_compatibility(db: &Connection) -> Result<()> {
+    check_schema_compatibility_against(db, &schema_semver())
+}
+
+/// The guts of `check_schema_compatibility`, taking the "supported"
+/// version as a parameter instead of always using this build's own, so
+/// the decision logic can be exercised directly in tests.
+fn check_schema_compatibility_against(db: &Connection, supported: &semver::Version) -> Result<()> {
+    match get_stored_semver(db)? {
+        Some(stored) if breaking_key(&stored) > breaking_key(supported) => {
+            throw!(Error::IncompatibleSchemaVersion {
+                stored,
+                supported: supported.clone(),
+            });
+        }
+        Some(stored) if stored >= *supported => {
+            // Nothing to do - this profile is already at (or, for a
+            // compatible bump, ahead of) what we'd write.
+        }
+        _ => set_stored_semver(db, supported)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{error_codes, ErrorCode};
+
+    fn ver(s: &str) -> semver::Version {
+        semver::Version::parse(s).unwrap()
+    }
+
+    fn test_db() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(CREATE_SCHEMA_SQL).unwrap();
+        db
+    }
+
+    #[test]
+    fn breaking_key_treats_major_bumps_as_breaking() {
+        assert!(breaking_key(&ver("2.0.0")) > breaking_key(&ver("1.9.9")));
+    }
+
+    #[test]
+    fn breaking_key_treats_0x_minor_bumps_as_breaking() {
+        assert!(breaking_key(&ver("0.2.0")) > breaking_key(&ver("0.1.9")));
+    }
+
+    #[test]
+    fn breaking_key_treats_0_0_z_patch_bumps_as_breaking() {
+        assert!(breaking_key(&ver("0.0.2")) > breaking_key(&ver("0.0.1")));
+    }
+
+    #[test]
+    fn breaking_key_ignores_patch_once_minor_is_nonzero() {
+        assert_eq!(breaking_key(&ver("0.1.5")), breaking_key(&ver("0.1.0")));
+    }
+
+    #[test]
+    fn first_open_records_the_supported_version() {
+        let db = test_db();
+        let supported = ver("0.3.0");
+        check_schema_compatibility_against(&db, &supported).unwrap();
+        assert_eq!(get_stored_semver(&db).unwrap(), Some(supported));
+    }
+
+    #[test]
+    fn compatible_bump_proceeds_and_advances_stored_version() {
+        let db = test_db();
+        set_stored_semver(&db, &ver("0.3.0")).unwrap();
+        let supported = ver("0.3.4");
+        check_schema_compatibility_against(&db, &supported).unwrap();
+        assert_eq!(get_stored_semver(&db).unwrap(), Some(supported));
+    }
+
+    #[test]
+    fn newer_major_is_refused() {
+        let db = test_db();
+        set_stored_semver(&db, &ver("2.0.0")).unwrap();
+        let err = check_schema_compatibility_against(&db, &ver("1.5.0")).unwrap_err();
+        assert_eq!(err.code(), error_codes::INCOMPATIBLE_SCHEMA);
+        // The stored version must be left untouched on refusal.
+        assert_eq!(get_stored_semver(&db).unwrap(), Some(ver("2.0.0")));
+    }
+
+    #[test]
+    fn newer_0x_minor_is_refused() {
+        let db = test_db();
+        set_stored_semver(&db, &ver("0.2.0")).unwrap();
+        let err = check_schema_compatibility_against(&db, &ver("0.1.0")).unwrap_err();
+        assert_eq!(err.code(), error_codes::INCOMPATIBLE_SCHEMA);
+    }
+}
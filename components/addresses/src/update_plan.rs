@@ -0,0 +1,250 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::address::InternalAddress;
+
+/// Result of reconciling the mirror (last-synced), local, and incoming
+/// remote copies of a single record.
+pub struct MergedRecord {
+    pub record: InternalAddress,
+    /// Set when at least one field genuinely diverged on both sides
+    /// relative to the mirror, so the caller can log/telemetry a real
+    /// conflict rather than a clean three-way merge.
+    pub had_conflict: bool,
+}
+
+/// Merges `local` and `remote` field-by-field against their common
+/// `mirror` ancestor (`None` if the record is new to one or both sides).
+///
+/// For each field: if only the local side changed, keep the local value;
+/// if only the remote side changed, keep the remote value; if
+/// both changed to the *same* value there's nothing to resolve; if both
+/// changed to *different* values that's a genuine conflict, broken by
+/// `time_last_modified` with remote winning ties.
+pub fn three_way_merge(
+    mirror: Option<&InternalAddress>,
+    local: &InternalAddress,
+    remote: &InternalAddress,
+) -> MergedRecord {
+    let mut merged = local.clone();
+    let mut had_conflict = false;
+    let remote_wins_ties = remote.time_last_modified >= local.time_last_modified;
+
+    macro_rules! merge_field {
+        ($field:ident) => {{
+            let l = local.$field.as_str();
+            let r = remote.$field.as_str();
+            merged.$field = match mirror.map(|m| m.$field.as_str()) {
+                Some(m) if l == m && r != m => r.to_string(),
+                Some(m) if r == m && l != m => l.to_string(),
+                Some(m) if l == m && r == m => l.to_string(),
+                _ if l == r => l.to_string(),
+                _ => {
+                    had_conflict = true;
+                    if remote_wins_ties { r.to_string() } else { l.to_string() }
+                }
+            };
+        }};
+    }
+
+    merge_field!(name);
+    merge_field!(organization);
+    merge_field!(street_address);
+    merge_field!(address_level3);
+    merge_field!(address_level2);
+    merge_field!(address_level1);
+    merge_field!(postal_code);
+    merge_field!(country);
+    merge_field!(tel);
+    merge_field!(email);
+
+    // `times_used` is a monotonically-increasing usage counter, so we
+    // fold in each side's *delta* relative to the mirror rather than
+    // overwriting with one side's absolute total and silently dropping
+    // the other side's increments.
+    let mirror_times_used = mirror.map_or(0, |m| m.times_used);
+    let local_delta = (local.times_used - mirror_times_used).max(0);
+    let remote_delta = (remote.times_used - mirror_times_used).max(0);
+    merged.times_used = mirror_times_used + local_delta + remote_delta;
+
+    merged.time_last_used = local.time_last_used.max(remote.time_last_used);
+    merged.time_last_modified = local.time_last_modified.max(remote.time_last_modified);
+    // The merge itself produces a new local state that still needs to be
+    // sent up, whether or not we hit a genuine conflict.
+    merged.sync_change_counter = 1;
+
+    MergedRecord { record: merged, had_conflict }
+}
+
+/// Accumulates the writes produced by reconciling a batch of incoming
+/// records, so the engine can apply them to the local store and mirror
+/// together, and know what still needs to go back up to the server.
+#[derive(Default)]
+pub struct UpdatePlan {
+    pub local_updates: Vec<InternalAddress>,
+    pub mirror_updates: Vec<InternalAddress>,
+    pub outgoing: Vec<InternalAddress>,
+    /// Guids where local and remote both diverged from the mirror on at
+    /// least one field and the tie had to be broken by policy, so callers
+    /// can report/log genuine conflicts instead of silent clean merges.
+    pub conflicted_guids: Vec<String>,
+}
+
+impl UpdatePlan {
+    /// Plans what to do with one incoming remote record, given whatever
+    /// local and mirror copies we already have for the same guid.
+    pub fn plan_incoming(
+        &mut self,
+        mirror: Option<InternalAddress>,
+        local: Option<InternalAddress>,
+        remote: InternalAddress,
+    ) {
+        match local {
+            Some(local) => {
+                let MergedRecord { record: merged, had_conflict } =
+                    three_way_merge(mirror.as_ref(), &local, &remote);
+                if had_conflict {
+                    self.conflicted_guids.push(merged.guid.clone());
+                }
+                // The merge may have taken fields from either side, so
+                // both stores need to be brought up to the merged state
+                // for them to converge.
+                self.local_updates.push(merged.clone());
+                self.outgoing.push(merged.clone());
+                self.mirror_updates.push(merged);
+            }
+            None => {
+                // Nothing local to reconcile against - the incoming
+                // record becomes the local and mirror state as-is.
+                self.local_updates.push(remote.clone());
+                self.mirror_updates.push(remote);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, time_last_modified: i64, times_used: i64) -> InternalAddress {
+        InternalAddress {
+            address: crate::address::Address {
+                guid: "guid0000001".into(),
+                name: name.into(),
+                ..Default::default()
+            },
+            time_last_modified,
+            times_used,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn local_only_change_keeps_local_value() {
+        let mirror = record("Alice", 1, 0);
+        let local = record("Alice Smith", 2, 0);
+        let remote = record("Alice", 1, 0);
+        let merged = three_way_merge(Some(&mirror), &local, &remote);
+        assert_eq!(merged.record.name, "Alice Smith");
+        assert!(!merged.had_conflict);
+    }
+
+    #[test]
+    fn remote_only_change_keeps_remote_value() {
+        let mirror = record("Alice", 1, 0);
+        let local = record("Alice", 1, 0);
+        let remote = record("Alice Smith", 2, 0);
+        let merged = three_way_merge(Some(&mirror), &local, &remote);
+        assert_eq!(merged.record.name, "Alice Smith");
+        assert!(!merged.had_conflict);
+    }
+
+    #[test]
+    fn both_sides_agree_is_not_a_conflict() {
+        let mirror = record("Alice", 1, 0);
+        let local = record("Alice Smith", 2, 0);
+        let remote = record("Alice Smith", 2, 0);
+        let merged = three_way_merge(Some(&mirror), &local, &remote);
+        assert_eq!(merged.record.name, "Alice Smith");
+        assert!(!merged.had_conflict);
+    }
+
+    #[test]
+    fn genuine_conflict_remote_wins_tie() {
+        let mirror = record("Alice", 1, 0);
+        let local = record("Alice L", 5, 0);
+        let remote = record("Alice R", 5, 0);
+        let merged = three_way_merge(Some(&mirror), &local, &remote);
+        assert_eq!(merged.record.name, "Alice R");
+        assert!(merged.had_conflict);
+    }
+
+    #[test]
+    fn genuine_conflict_newer_side_wins() {
+        let mirror = record("Alice", 1, 0);
+        let local = record("Alice L", 10, 0);
+        let remote = record("Alice R", 5, 0);
+        let merged = three_way_merge(Some(&mirror), &local, &remote);
+        assert_eq!(merged.record.name, "Alice L");
+        assert!(merged.had_conflict);
+
+        let local = record("Alice L", 5, 0);
+        let remote = record("Alice R", 10, 0);
+        let merged = three_way_merge(Some(&mirror), &local, &remote);
+        assert_eq!(merged.record.name, "Alice R");
+        assert!(merged.had_conflict);
+    }
+
+    #[test]
+    fn times_used_accumulates_deltas_from_both_sides() {
+        let mirror = record("Alice", 1, 10);
+        let local = record("Alice", 2, 13); // +3 locally
+        let remote = record("Alice", 1, 15); // +5 remotely
+        let merged = three_way_merge(Some(&mirror), &local, &remote);
+        assert_eq!(merged.record.times_used, 10 + 3 + 5);
+    }
+
+    #[test]
+    fn no_mirror_treats_matching_fields_as_agreement() {
+        let local = record("Alice", 1, 2);
+        let remote = record("Alice", 1, 3);
+        let merged = three_way_merge(None, &local, &remote);
+        assert_eq!(merged.record.name, "Alice");
+        assert!(!merged.had_conflict);
+        // With no mirror, times_used deltas are taken relative to zero.
+        assert_eq!(merged.record.times_used, 2 + 3);
+    }
+
+    #[test]
+    fn no_mirror_with_differing_fields_is_a_conflict() {
+        let local = record("Alice L", 1, 0);
+        let remote = record("Alice R", 2, 0);
+        let merged = three_way_merge(None, &local, &remote);
+        assert_eq!(merged.record.name, "Alice R");
+        assert!(merged.had_conflict);
+    }
+
+    #[test]
+    fn plan_incoming_with_no_local_adopts_remote_as_is() {
+        let mut plan = UpdatePlan::default();
+        let remote = record("Alice", 1, 0);
+        plan.plan_incoming(None, None, remote.clone());
+        assert_eq!(plan.local_updates, vec![remote.clone()]);
+        assert_eq!(plan.mirror_updates, vec![remote]);
+        assert!(plan.outgoing.is_empty());
+    }
+
+    #[test]
+    fn plan_incoming_with_local_emits_merged_everywhere() {
+        let mut plan = UpdatePlan::default();
+        let mirror = record("Alice", 1, 0);
+        let local = record("Alice L", 2, 0);
+        let remote = record("Alice", 1, 0);
+        plan.plan_incoming(Some(mirror), Some(local), remote);
+        assert_eq!(plan.local_updates[0].name, "Alice L");
+        assert_eq!(plan.outgoing[0].name, "Alice L");
+        assert_eq!(plan.mirror_updates[0].name, "Alice L");
+    }
+}